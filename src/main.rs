@@ -30,6 +30,46 @@ const SGX_ENCL_SECS_EVICTED: u64 = 1 << 2;
 const SGX_ENCL_SUSPEND: u64 = 1 << 3;
 const SGX_ENCL_DEAD: u64 = 1 << 4;
 
+/// Column the enclave table is sorted on. Cycled with the `s` key.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SortKey {
+    Rss,
+    Swap,
+    Eadds,
+    Virt,
+}
+
+impl SortKey {
+    fn next(self) -> Self {
+        match self {
+            SortKey::Rss => SortKey::Swap,
+            SortKey::Swap => SortKey::Eadds,
+            SortKey::Eadds => SortKey::Virt,
+            SortKey::Virt => SortKey::Rss,
+        }
+    }
+}
+
+impl Display for SortKey {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            SortKey::Rss => write!(f, "RSS"),
+            SortKey::Swap => write!(f, "SWAP"),
+            SortKey::Eadds => write!(f, "EADDs"),
+            SortKey::Virt => write!(f, "VIRT"),
+        }
+    }
+}
+
+/// Send `SIGTERM` (or `SIGKILL` when `force`) to `pid`, for the `k`/`K`
+/// operational keys.
+fn kill_enclave(pid: u64, force: bool) {
+    let sig = if force { libc::SIGKILL } else { libc::SIGTERM };
+    unsafe {
+        libc::kill(pid as libc::pid_t, sig);
+    }
+}
+
 /// INIT:  SGX_ENCL_INITIALIZED
 ///
 /// DEBUG: SGX_ENCL_DEBUG
@@ -91,8 +131,13 @@ impl Sub for Memory {
 /// VIRT > EADDs > RSS
 #[derive(Debug)]
 struct Enclave {
-    /// the coresponding process id
-    pid: u64,
+    /// the processes sharing this enclave via its `mm_list`
+    ///
+    /// the enclave is only released once every PID here has unmapped it,
+    /// so more than one entry means the enclave is shared (e.g. across a
+    /// runtime's worker processes). The first entry is shown on the primary
+    /// row and is the PID signalled when the enclave is selected and killed.
+    pids: Vec<u64>,
 
     /// global enclave unique id
     eid: u64,
@@ -118,36 +163,165 @@ struct Enclave {
     /// memory swaped to DRAM. It may be swaped back to EPC later
     swap: Memory,
     state: EnclaveState,
+
+    /// pages evicted to DRAM (EWB) since the last tick, derived from the
+    /// previous `swap` snapshot in `GlobalStats::prev_enclave_stats`
+    swap_out_rate: Memory,
+    /// pages loaded back into EPC (ELDU) since the last tick, derived from
+    /// the previous `swap` snapshot in `GlobalStats::prev_enclave_stats`
+    swap_in_rate: Memory,
 }
 
-impl Display for Enclave {
-    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
-        // "/proc/54142/cmdline"
-        let mut path = PathBuf::from("/proc");
-        path.push(self.pid.to_string());
-        path.push("cmdline");
+/// Combined swap-in + swap-out rate above which an enclave's row is
+/// highlighted as actively thrashing.
+const SWAP_RATE_WARN_THRESHOLD: u64 = 1024;
 
-        let command: String = match fs::read(path.as_path()) {
+/// "/proc/<pid>/cmdline" of `pid`, or "" if the process is already gone.
+fn read_command(pid: u64) -> String {
+    let mut path = PathBuf::from("/proc");
+    path.push(pid.to_string());
+    path.push("cmdline");
+
+    match fs::read(path.as_path()) {
+        Err(_) => "".to_string(),
+        Ok(v) => match String::from_utf8(v) {
+            Ok(x) => x,
             Err(_) => "".to_string(),
-            Ok(v) => match String::from_utf8(v) {
-                Ok(x) => x,
-                Err(_) => "".to_string(),
-            },
-        };
+        },
+    }
+}
 
+impl Display for Enclave {
+    /// Formats only the primary row (`self.pids[0]`). Continuation rows for
+    /// the rest of a shared enclave's `mm_list` are written separately by
+    /// `GlobalStats::draw`, which is where the collapse toggle lives.
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        let pid = self.pids[0];
+        let thrashing =
+            self.swap_in_rate.0 + self.swap_out_rate.0 > SWAP_RATE_WARN_THRESHOLD;
+        if thrashing {
+            write!(f, "{}", color::Fg(color::Red))?;
+        }
         write!(
             f,
-            "{:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>10} {}\n\r",
+            "{:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>10} {}\n\r",
             self.eid,
-            self.pid,
+            pid,
             self.virt,
             self.eadds,
             self.rss,
             self.swap,
             self.va,
+            self.swap_out_rate,
+            self.swap_in_rate,
             self.state,
-            command
-        )
+            read_command(pid),
+        )?;
+        if thrashing {
+            write!(f, "{}", color::Fg(color::Reset))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Per-NUMA-node EPC breakdown, read from
+/// `/sys/devices/system/node/node*/x86/sgx_total_bytes`
+///
+/// `free` is `None` when the driver does not expose a matching
+/// `sgx_free_bytes` attribute for the node.
+#[derive(Debug)]
+struct NodeStats {
+    node_id: u64,
+    total: Memory,
+    free: Option<Memory>,
+}
+
+/// Glob `/sys/devices/system/node/node*/x86/sgx_total_bytes` (and the
+/// sibling `sgx_free_bytes`, if present) to build a per-node EPC breakdown.
+///
+/// Returns an empty `Vec` on machines without per-node SGX accounting
+/// (e.g. single-socket, or an older driver).
+fn read_node_stats() -> Vec<NodeStats> {
+    let mut nodes = vec![];
+    let entries = match fs::read_dir("/sys/devices/system/node") {
+        Ok(entries) => entries,
+        Err(_) => return nodes,
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let node_id = match name.to_str().and_then(|s| s.strip_prefix("node")) {
+            Some(id_str) => match id_str.parse::<u64>() {
+                Ok(id) => id,
+                Err(_) => continue,
+            },
+            None => continue,
+        };
+        let total_bytes = match fs::read_to_string(entry.path().join("x86/sgx_total_bytes")) {
+            Ok(s) => match s.trim().parse::<u64>() {
+                Ok(b) => b,
+                Err(_) => continue,
+            },
+            Err(_) => continue,
+        };
+        let free = fs::read_to_string(entry.path().join("x86/sgx_free_bytes"))
+            .ok()
+            .and_then(|s| s.trim().parse::<u64>().ok())
+            .map(|b| Memory(b >> 10));
+        nodes.push(NodeStats {
+            node_id,
+            total: Memory(total_bytes >> 10),
+            free,
+        });
+    }
+    nodes.sort_by_key(|n| n.node_id);
+    nodes
+}
+
+/// Number of ticks kept by each [`History`] ring buffer (120s at the
+/// default 1s tick).
+const HISTORY_LEN: usize = 120;
+
+const SPARKLINE_GLYPHS: [char; 8] =
+    ['\u{2581}', '\u{2582}', '\u{2583}', '\u{2584}', '\u{2585}', '\u{2586}', '\u{2587}', '\u{2588}'];
+
+/// A fixed-size ring buffer of per-tick samples, rendered as a one-line
+/// sparkline to reveal reclaimer pressure trends that a single
+/// instantaneous rate can't.
+#[derive(Debug)]
+struct History {
+    samples: std::collections::VecDeque<u64>,
+}
+
+impl History {
+    fn new() -> Self {
+        History {
+            samples: std::collections::VecDeque::with_capacity(HISTORY_LEN),
+        }
+    }
+
+    fn push(&mut self, v: u64) {
+        if self.samples.len() == HISTORY_LEN {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(v);
+    }
+
+    /// Render the samples as Unicode block glyphs, scaled to the max
+    /// sample in the window.
+    fn sparkline(&self) -> String {
+        let max = self.samples.iter().copied().max().unwrap_or(0);
+        self.samples
+            .iter()
+            .map(|&v| {
+                if max == 0 {
+                    SPARKLINE_GLYPHS[0]
+                } else {
+                    let idx = (v * (SPARKLINE_GLYPHS.len() as u64 - 1) / max) as usize;
+                    SPARKLINE_GLYPHS[idx]
+                }
+            })
+            .collect()
     }
 }
 
@@ -174,6 +348,53 @@ struct GlobalStats {
     ///
     /// This variable will track all pages in DRAM, freed when a enclave is released.
     sgx_freed_backing_pages: Memory,
+
+    /// Pages poisoned by a hardware memory error and recovered by the driver.
+    ///
+    /// These pages are removed from the allocator and will never be
+    /// reclaimable again, so a non-zero value here means permanently
+    /// reduced EPC capacity.
+    sgx_nr_poison_pages: Memory,
+
+    /// Per-NUMA-node EPC breakdown, refreshed every `draw`.
+    nodes: Vec<NodeStats>,
+
+    /// Recent history of `ewb_speed`, for the reclaimer-pressure sparkline.
+    ewb_history: History,
+    /// Recent history of `eldu_speed`, for the reclaimer-pressure sparkline.
+    eldu_history: History,
+    /// Recent history of used EPC, for the reclaimer-pressure sparkline.
+    epc_used_history: History,
+
+    /// Column the enclave table is currently sorted on.
+    sort_key: SortKey,
+    /// When set, the table is sorted in descending order (largest first);
+    /// otherwise ascending. Toggled with the `S` key.
+    sort_reverse: bool,
+    /// Bitmask of `SGX_ENCL_*` state bits to hide, toggled by state-filter keys.
+    state_filter: u64,
+    /// When set, only show enclaves with `SUS` or `EVICT` set.
+    only_pressured: bool,
+    /// `eid` of the currently selected enclave, re-resolved against the
+    /// sorted/filtered table every `draw` so the selection follows the
+    /// enclave, not a row position that a volatile sort key can reshuffle.
+    selected_eid: Option<u64>,
+    /// Primary PID of the currently selected enclave, refreshed every `draw`.
+    selected_pid: Option<u64>,
+    /// `eid`s in last-drawn sorted/filtered order, so `Key::Up`/`Key::Down`
+    /// (which run between draws) can move the selection without a stale index.
+    visible_eids: Vec<u64>,
+    /// Armed by `k`/`K`; `Some(force)` means a kill is awaiting the `y`
+    /// confirmation keystroke, where `force` selects SIGKILL over SIGTERM.
+    pending_kill: Option<bool>,
+    /// When set, only the first PID of a shared enclave is printed,
+    /// collapsing the continuation rows. Toggled by the `m` key.
+    collapse_shared: bool,
+
+    /// Previous `swap` per enclave, keyed by `eid`, used to derive
+    /// per-enclave swap-in/swap-out rates. Entries for released enclaves
+    /// are dropped every tick.
+    prev_enclave_stats: std::collections::HashMap<u64, Memory>,
     screen: termion::screen::AlternateScreen<RawTerminal<std::io::Stdout>>,
 }
 
@@ -190,6 +411,21 @@ impl GlobalStats {
             sgx_ewb_cnt: None,
             sgx_eldu_cnt: None,
             sgx_freed_backing_pages: Memory(0),
+            sgx_nr_poison_pages: Memory(0),
+            nodes: vec![],
+            ewb_history: History::new(),
+            eldu_history: History::new(),
+            epc_used_history: History::new(),
+            sort_key: SortKey::Rss,
+            sort_reverse: true,
+            state_filter: 0,
+            only_pressured: false,
+            selected_eid: None,
+            selected_pid: None,
+            visible_eids: vec![],
+            pending_kill: None,
+            collapse_shared: false,
+            prev_enclave_stats: std::collections::HashMap::new(),
             screen: AlternateScreen::from(stdout().into_raw_mode().unwrap()),
         }
     }
@@ -227,6 +463,8 @@ impl GlobalStats {
         let sgx_ewb_cnt_new = Memory(iter.next().unwrap() << 2);
         let sgx_eldu_cnt_new = Memory(iter.next().unwrap() << 2);
         self.sgx_freed_backing_pages = Memory(iter.next().unwrap() << 2);
+        // Older drivers predate the poison-page counter; don't fail to start over it.
+        self.sgx_nr_poison_pages = iter.next().map(|v| Memory(v << 2)).unwrap_or(Memory(0));
 
         let eadd_speed = {
             match self.sgx_pages_alloced {
@@ -280,15 +518,57 @@ impl GlobalStats {
             ewb_speed, eldu_speed
         )
         .unwrap();
+
+        self.ewb_history.push(ewb_speed.0);
+        self.eldu_history.push(eldu_speed.0);
+        self.epc_used_history
+            .push((self.sgx_nr_total_epc_pages - self.sgx_nr_free_pages).0);
+        write!(self.screen, "ewb  [{}]\n\r", self.ewb_history.sparkline()).unwrap();
+        write!(self.screen, "eldu [{}]\n\r", self.eldu_history.sparkline()).unwrap();
         write!(
             self.screen,
-            "EPC mem: {:>8} total, {:>8} free, {:>8} used, {:>8} VA\n\r",
+            "epc  [{}]\n\r",
+            self.epc_used_history.sparkline()
+        )
+        .unwrap();
+
+        if self.sgx_nr_poison_pages.0 > 0 {
+            write!(self.screen, "{}", color::Fg(color::Red)).unwrap();
+        }
+        write!(
+            self.screen,
+            "EPC mem: {:>8} total, {:>8} free, {:>8} used, {:>8} VA, {:>8} poisoned\n\r",
             self.sgx_nr_total_epc_pages,
             self.sgx_nr_free_pages,
             self.sgx_nr_total_epc_pages - self.sgx_nr_free_pages,
             self.sgx_va_pages_cnt,
+            self.sgx_nr_poison_pages,
         )
         .unwrap();
+        if self.sgx_nr_poison_pages.0 > 0 {
+            write!(self.screen, "{}", style::Reset).unwrap();
+        }
+
+        self.nodes = read_node_stats();
+        for n in &self.nodes {
+            match n.free {
+                Some(free) => write!(
+                    self.screen,
+                    "  node{}: {:>8} total, {:>8} free, {:>8} used\n\r",
+                    n.node_id,
+                    n.total,
+                    free,
+                    Memory(n.total.0.saturating_sub(free.0)),
+                )
+                .unwrap(),
+                None => write!(
+                    self.screen,
+                    "  node{}: {:>8} total\n\r",
+                    n.node_id, n.total
+                )
+                .unwrap(),
+            }
+        }
 
         let swap_size = match self.sgx_ewb_cnt {
             None => Memory(0),
@@ -302,7 +582,28 @@ impl GlobalStats {
 
         write!(
             self.screen,
-            "\n\r{}{}{:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>10} {}{}\n\r",
+            "sort: {}{}\n\r",
+            self.sort_key,
+            if self.sort_reverse { " (desc)" } else { " (asc)" }
+        )
+        .unwrap();
+
+        if let Some(force) = self.pending_kill {
+            let sig = if force { "SIGKILL" } else { "SIGTERM" };
+            write!(
+                self.screen,
+                "{}press y to send {} to pid {:?}, any other key cancels{}\n\r",
+                color::Fg(color::Red),
+                sig,
+                self.selected_pid,
+                color::Fg(color::Reset),
+            )
+            .unwrap();
+        }
+
+        write!(
+            self.screen,
+            "\n\r{}{}{:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>10} {}{}\n\r",
             color::Fg(color::Black),
             color::Bg(color::White),
             "EID",
@@ -312,15 +613,85 @@ impl GlobalStats {
             "RSS",
             "SWAP",
             "VA",
+            "SWAP-OUT",
+            "SWAP-IN",
             "state",
             "Command",
             style::Reset
         )
         .unwrap();
 
-        let ev: Vec<Enclave> = read_sgx_enclave().expect("/proc/sgx_enclaves not found");
-        for e in ev {
-            write!(self.screen, "{}", e).unwrap()
+        let mut ev: Vec<Enclave> = read_sgx_enclave().expect("/proc/sgx_enclaves not found");
+
+        let mut new_prev_enclave_stats = std::collections::HashMap::with_capacity(ev.len());
+        for e in ev.iter_mut() {
+            let prev_swap = self.prev_enclave_stats.get(&e.eid).copied();
+            let (swap_in_rate, swap_out_rate) = match prev_swap {
+                None => (Memory(0), Memory(0)),
+                Some(prev_swap) => {
+                    if e.swap.0 > prev_swap.0 {
+                        (Memory(0), Memory(e.swap.0 - prev_swap.0))
+                    } else {
+                        (Memory(prev_swap.0 - e.swap.0), Memory(0))
+                    }
+                }
+            };
+            e.swap_in_rate = swap_in_rate;
+            e.swap_out_rate = swap_out_rate;
+            new_prev_enclave_stats.insert(e.eid, e.swap);
+        }
+        self.prev_enclave_stats = new_prev_enclave_stats;
+
+        ev.retain(|e| {
+            if self.state_filter != 0 && (e.state.0 & self.state_filter) != 0 {
+                return false;
+            }
+            if self.only_pressured
+                && (e.state.0 & (SGX_ENCL_SUSPEND | SGX_ENCL_SECS_EVICTED)) == 0
+            {
+                return false;
+            }
+            true
+        });
+
+        ev.sort_by_key(|e| match self.sort_key {
+            SortKey::Rss => e.rss.0,
+            SortKey::Swap => e.swap.0,
+            SortKey::Eadds => e.eadds.0,
+            SortKey::Virt => e.virt.0,
+        });
+        if self.sort_reverse {
+            ev.reverse();
+        }
+
+        self.visible_eids = ev.iter().map(|e| e.eid).collect();
+        let selected_idx = self
+            .selected_eid
+            .and_then(|eid| ev.iter().position(|e| e.eid == eid))
+            .or(if ev.is_empty() { None } else { Some(0) });
+        self.selected_eid = selected_idx.map(|i| ev[i].eid);
+        self.selected_pid = selected_idx.map(|i| ev[i].pids[0]);
+
+        for (i, e) in ev.iter().enumerate() {
+            if Some(i) == selected_idx {
+                write!(self.screen, "{}", style::Invert).unwrap();
+            }
+            write!(self.screen, "{}", e).unwrap();
+            if Some(i) == selected_idx {
+                write!(self.screen, "{}", style::Reset).unwrap();
+            }
+            if !self.collapse_shared {
+                for pid in &e.pids[1..] {
+                    write!(
+                        self.screen,
+                        "{:>8} {:>8} {}\n\r",
+                        "",
+                        pid,
+                        read_command(*pid)
+                    )
+                    .unwrap();
+                }
+            }
         }
         self.screen.flush().unwrap();
     }
@@ -334,11 +705,18 @@ fn read_sgx_enclave() -> Result<Vec<Enclave>, std::io::Error> {
         .split(|x| x == &10 || x == &13)
         .filter(|line| line.len() != 0)
         .map(|line| {
-            let mut iter = line
-                .split(|x| x == &32 || x == &10 || x == &13)
-                .map(|x| x.iter().fold(0 as u64, |acc, x| acc * 10 + (x - 48) as u64));
+            let mut fields = line.split(|x| x == &32 || x == &10 || x == &13);
+            // mm_list PIDs are comma-separated in the first field, e.g. "123,456"
+            let pids: Vec<u64> = fields
+                .next()
+                .unwrap()
+                .split(|x| x == &44)
+                .map(|x| x.iter().fold(0 as u64, |acc, x| acc * 10 + (x - 48) as u64))
+                .collect();
+            let mut iter =
+                fields.map(|x| x.iter().fold(0 as u64, |acc, x| acc * 10 + (x - 48) as u64));
             Enclave {
-                pid: iter.next().unwrap(),
+                pids,
                 eid: iter.next().unwrap(),
                 virt: Memory(iter.next().unwrap() >> 10),
                 eadds: Memory(iter.next().unwrap() << 2),
@@ -347,6 +725,8 @@ fn read_sgx_enclave() -> Result<Vec<Enclave>, std::io::Error> {
                 state: EnclaveState(iter.next().unwrap()),
                 swap: Memory(iter.next().unwrap() << 2),
                 //startTime
+                swap_out_rate: Memory(0),
+                swap_in_rate: Memory(0),
             }
         })
         .collect();
@@ -369,7 +749,65 @@ fn main() -> Result<(), Box<dyn Error>> {
                     g.reset();
                     break;
                 }
-                _ => {}
+                Key::Char('k') => {
+                    g.pending_kill = Some(false);
+                }
+                Key::Char('K') => {
+                    g.pending_kill = Some(true);
+                }
+                Key::Char('y') => {
+                    if let (Some(force), Some(pid)) = (g.pending_kill.take(), g.selected_pid) {
+                        kill_enclave(pid, force);
+                    }
+                }
+                other => {
+                    // any key other than 'y' cancels an armed kill confirmation
+                    g.pending_kill = None;
+                    match other {
+                        Key::Char('m') => {
+                            g.collapse_shared = !g.collapse_shared;
+                        }
+                        Key::Char('s') => {
+                            g.sort_key = g.sort_key.next();
+                        }
+                        Key::Char('S') => {
+                            g.sort_reverse = !g.sort_reverse;
+                        }
+                        Key::Char('d') => {
+                            g.state_filter ^= SGX_ENCL_DEAD;
+                        }
+                        Key::Char('a') => {
+                            g.only_pressured = !g.only_pressured;
+                        }
+                        Key::Up => {
+                            if let Some(eid) = g.selected_eid {
+                                if let Some(pos) =
+                                    g.visible_eids.iter().position(|&e| e == eid)
+                                {
+                                    if pos > 0 {
+                                        g.selected_eid = Some(g.visible_eids[pos - 1]);
+                                    }
+                                }
+                            } else if let Some(&first) = g.visible_eids.first() {
+                                g.selected_eid = Some(first);
+                            }
+                        }
+                        Key::Down => {
+                            if let Some(eid) = g.selected_eid {
+                                if let Some(pos) =
+                                    g.visible_eids.iter().position(|&e| e == eid)
+                                {
+                                    if pos + 1 < g.visible_eids.len() {
+                                        g.selected_eid = Some(g.visible_eids[pos + 1]);
+                                    }
+                                }
+                            } else if let Some(&first) = g.visible_eids.first() {
+                                g.selected_eid = Some(first);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
             },
             Event::Tick => {
                 g.draw();